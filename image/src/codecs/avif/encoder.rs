@@ -12,6 +12,7 @@ use crate::{ImageError, ImageResult};
 use crate::buffer::ConvertBuffer;
 use crate::color::{FromColor, Luma, LumaA, Bgr, Bgra, Rgb, Rgba};
 use crate::error::{EncodingError, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind};
+use crate::image::ImageEncoder;
 
 use bytemuck::{Pod, PodCastError, try_cast_slice, try_cast_slice_mut};
 use num_traits::Zero;
@@ -24,7 +25,7 @@ use rgb::AsPixels;
 pub struct AvifEncoder<W> {
     inner: W,
     fallback: Vec<u8>,
-    config: Config
+    config: Config,
 }
 
 /// An enumeration over supported AVIF color spaces
@@ -72,7 +73,7 @@ impl<W: Write> AvifEncoder<W> {
                 speed,
                 premultiplied_alpha: false,
                 color_space: ravif::ColorSpace::RGB,
-            } 
+            },
         }
     }
 
@@ -82,29 +83,75 @@ impl<W: Write> AvifEncoder<W> {
         self
     }
 
+    /// Set the quality of the alpha channel separately from the color quality.
+    ///
+    /// `quality` accepts a value in the range 0-100, where 0 is the worst and 100 is the best.
+    /// By default, the alpha quality matches the color quality passed to
+    /// [`new_with_speed_quality`](Self::new_with_speed_quality).
+    pub fn with_alpha_quality(mut self, quality: u8) -> Self {
+        self.config.alpha_quality = min(quality, 100);
+        self
+    }
+
+    /// Mark the input data as already having its color channels premultiplied by alpha.
+    ///
+    /// This only affects the metadata written to the AVIF container; it does not itself
+    /// premultiply the pixel data. By default, input is assumed to be straight (non-premultiplied)
+    /// alpha.
+    pub fn with_premultiplied_alpha(mut self, premultiplied_alpha: bool) -> Self {
+        self.config.premultiplied_alpha = premultiplied_alpha;
+        self
+    }
+
     /// Encode image data with the indicated color type.
     ///
     /// The encoder currently requires all data to be RGBA8, it will be converted internally if
     /// necessary. When data is suitably aligned, i.e. u16 channels to two bytes, then the
     /// conversion may be more efficient.
     pub fn write_image(mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        let encoded = self.encode_avif(data, width, height, color)?;
+        self.inner.write_all(&encoded)?;
+        Ok(())
+    }
+
+    /// Encode image data with the indicated color type into a caller-provided buffer, returning
+    /// the number of bytes written.
+    ///
+    /// Unlike [`write_image`](Self::write_image), the result is copied into `out` rather than
+    /// written through the inner `Write`, which is useful for callers that manage their own
+    /// memory. The encoded container is still built in an intermediate `Vec` first, as with
+    /// `write_image`. If `out` is not large enough to hold the encoded data, an
+    /// error is returned that reports the number of bytes that would have been required, so the
+    /// caller can resize `out` and retry.
+    pub fn encode_to_buf(mut self, out: &mut [u8], data: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<usize> {
+        let encoded = self.encode_avif(data, width, height, color)?;
+        if out.len() < encoded.len() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "output buffer too small: needed {} bytes, got {}",
+                    encoded.len(),
+                    out.len(),
+                )),
+            )));
+        }
+        out[..encoded.len()].copy_from_slice(&encoded);
+        Ok(encoded.len())
+    }
+
+    /// Encode the image data into an owned buffer containing the finished AVIF container.
+    fn encode_avif(&mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<Vec<u8>> {
         self.set_color(color);
         let config = self.config;
+
         // `ravif` needs strongly typed data so let's convert. We can either use a temporarily
         // owned version in our own buffer or zero-copy if possible by using the input buffer.
         // This requires going through `rgb`.
         let buffer = self.encode_as_img(data, width, height, color)?;
-        let (data, _color_size, _alpha_size) = encode_rgba(buffer, &config)
+        encode_rgba(buffer, &config)
+            .map(|(data, _color_size, _alpha_size)| data)
             .map_err(|err| ImageError::Encoding(
                 EncodingError::new(ImageFormat::Avif.into(), err)
-            ))?;
-        self.inner.write_all(&data)?;
-        Ok(())
-    }
-
-    // Does not currently do anything. Mirrors behaviour of old config function.
-    fn set_color(&mut self, _color: ColorType) {
-        // self.config.color_space = ColorSpace::RGB;
+            ))
     }
 
     fn encode_as_img<'buf>(&'buf mut self, data: &'buf [u8], width: u32, height: u32, color: ColorType)
@@ -198,7 +245,9 @@ impl<W: Write> AvifEncoder<W> {
                 let image = try_from_raw::<Bgra<u8>>(data, width, height)?;
                 Ok(convert_into(&mut self.fallback, image))
             }
-            // we need to really convert data..
+            // we need to really convert data.. note that the pinned `ravif` dependency only
+            // ever emits an 8-bit bitstream, so 16-bit input is downsampled to 8 bits here like
+            // every other non-8-bit color type; there is no higher-depth encode path to opt into.
             ColorType::L16 => {
                 let buffer = cast_buffer(data)?;
                 let image = try_from_raw::<Luma<u16>>(&buffer, width, height)?;
@@ -226,4 +275,124 @@ impl<W: Write> AvifEncoder<W> {
                 )))
         }
     }
+
+    // Does not currently do anything. Mirrors behaviour of old config function.
+    fn set_color(&mut self, _color: ColorType) {
+        // self.config.color_space = ColorSpace::RGB;
+    }
+
+}
+
+impl<W: Write> ImageEncoder for AvifEncoder<W> {
+    /// Forwards to the inherent [`AvifEncoder::write_image`], encoding image data with the
+    /// indicated color type.
+    ///
+    /// The encoder currently requires all data to be RGBA8, it will be converted internally if
+    /// necessary. When data is suitably aligned, i.e. u16 channels to two bytes, then the
+    /// conversion may be more efficient.
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.write_image(buf, width, height, color_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_alpha_quality_overrides_color_quality() {
+        let encoder = AvifEncoder::new_with_speed_quality(Vec::new(), 10, 80)
+            .with_alpha_quality(42);
+        assert_eq!(encoder.config.alpha_quality, 42);
+        assert_eq!(encoder.config.quality, 80);
+    }
+
+    #[test]
+    fn with_alpha_quality_clamps_to_100() {
+        let encoder = AvifEncoder::new(Vec::new()).with_alpha_quality(255);
+        assert_eq!(encoder.config.alpha_quality, 100);
+    }
+
+    #[test]
+    fn with_premultiplied_alpha_sets_the_flag() {
+        assert!(!AvifEncoder::new(Vec::new()).config.premultiplied_alpha);
+        let encoder = AvifEncoder::new(Vec::new()).with_premultiplied_alpha(true);
+        assert!(encoder.config.premultiplied_alpha);
+    }
+
+    fn rgba_checkerboard(width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![0u8; (width * height * 4) as usize];
+        for (i, px) in data.chunks_exact_mut(4).enumerate() {
+            let v = if i % 2 == 0 { 255 } else { 0 };
+            px.copy_from_slice(&[v, v, v, 255]);
+        }
+        data
+    }
+
+    #[test]
+    fn encode_to_buf_reports_required_len_when_too_small() {
+        let data = rgba_checkerboard(4, 4);
+        let mut required = Vec::new();
+        AvifEncoder::new(&mut required)
+            .write_image(&data, 4, 4, ColorType::Rgba8)
+            .unwrap();
+        let mut out = vec![0u8; 1];
+        let err = AvifEncoder::new(Vec::new())
+            .encode_to_buf(&mut out, &data, 4, 4, ColorType::Rgba8)
+            .unwrap_err();
+        assert!(matches!(err, ImageError::Parameter(_)));
+        assert!(err.to_string().contains(&format!("needed {} bytes", required.len())));
+    }
+
+    #[test]
+    fn encode_to_buf_matches_write_image_byte_for_byte() {
+        let data = rgba_checkerboard(8, 8);
+
+        let mut via_write_image = Vec::new();
+        AvifEncoder::new(&mut via_write_image)
+            .write_image(&data, 8, 8, ColorType::Rgba8)
+            .unwrap();
+
+        let mut buf = vec![0u8; via_write_image.len()];
+        let written = AvifEncoder::new(Vec::new())
+            .encode_to_buf(&mut buf, &data, 8, 8, ColorType::Rgba8)
+            .unwrap();
+
+        assert_eq!(written, via_write_image.len());
+        assert_eq!(&buf[..written], &via_write_image[..]);
+    }
+
+    #[test]
+    fn rgba8_zero_copy_path_matches_rgb8_converted_path() {
+        // `encode_as_img` takes a zero-copy path for `Rgba8` that is distinct from the bulk
+        // `convert()` path every other color type goes through. For fully opaque pixels the two
+        // paths see the same color values, so encoding the same image as Rgb8 (always converted)
+        // and as Rgba8 (zero-copy) should agree; this guards the zero-copy fast path against
+        // silently diverging from the general conversion path.
+        let width = 6;
+        let height = 6;
+        let rgba = rgba_checkerboard(width, height);
+        let rgb: Vec<u8> = rgba
+            .chunks_exact(4)
+            .flat_map(|px| [px[0], px[1], px[2]])
+            .collect();
+
+        let mut via_rgb = Vec::new();
+        AvifEncoder::new(&mut via_rgb)
+            .write_image(&rgb, width, height, ColorType::Rgb8)
+            .unwrap();
+
+        let mut via_rgba = Vec::new();
+        AvifEncoder::new(&mut via_rgba)
+            .write_image(&rgba, width, height, ColorType::Rgba8)
+            .unwrap();
+
+        assert_eq!(via_rgb, via_rgba);
+    }
 }